@@ -1,9 +1,14 @@
 use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fmt::Debug;
 use std::mem;
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 
 use lazy_static::lazy_static;
 
-use percent_encoding::percent_decode;
+use percent_encoding::{percent_decode, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use regex::Regex;
 
 use crate::shell;
@@ -50,23 +55,162 @@ pub fn escape_filename<'t>(filename: &'t str) -> Cow<'t, str> {
     SPECIAL_CHARS.replace_all(&*filename, r"\$0")
 }
 
-/// Decode a file URI.
+/// Escape a path for the nvim command line, the `OsStr`-preserving counterpart
+/// of [`escape_filename`].
+///
+/// The same ASCII characters are backslash-escaped. On UNIX the path is
+/// processed byte by byte so non-UTF-8 filenames — which both nvim and the
+/// filesystem accept — survive unchanged; on other platforms the path is
+/// converted lossily to UTF-8 and passed to [`escape_filename`].
+pub fn escape_path(path: &Path) -> OsString {
+    fn is_allowed(b: u8) -> bool {
+        b.is_ascii_alphanumeric()
+            || matches!(b, b'.' | b'_' | b'-')
+            || if cfg!(target_os = "windows") {
+                // `:` and `\` are valid path components on Windows.
+                matches!(b, b':' | b'\\')
+            } else {
+                b == b'/'
+            }
+    }
+
+    #[cfg(unix)]
+    {
+        let bytes = path.as_os_str().as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            if b.is_ascii() && !is_allowed(b) {
+                out.push(b'\\');
+            }
+            out.push(b);
+        }
+        OsString::from_vec(out)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = is_allowed;
+        OsString::from(escape_filename(&path.to_string_lossy()).into_owned())
+    }
+}
+
+/// Returns `true` if `path` starts with a `/C:/`-style drive letter, as found
+/// in the path component of a Windows `file://` URI.
+#[cfg(windows)]
+fn starts_with_drive_letter(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 4
+        && bytes[0] == b'/'
+        && bytes[1].is_ascii_alphabetic()
+        && bytes[2] == b':'
+        && bytes[3] == b'/'
+}
+
+/// Decode a [RFC 8089](https://tools.ietf.org/html/rfc8089) `file` URI.
+///
+/// The scheme is followed by an optional authority between `//` and the next
+/// `/`; an empty authority or `localhost` denotes the local machine. On Windows
+/// any other host is treated as a UNC share; on UNIX the authority is ignored
+/// and the path is taken as local. Only the path component is percent-decoded.
+/// Returns `None` when the scheme is not `file`, or, on Windows, when the
+/// decoded path is not valid UTF-8.
 ///
 ///   - On UNIX: `file:///path/to/a%20file.ext` -> `/path/to/a file.ext`
 ///   - On Windows: `file:///C:/path/to/a%20file.ext` -> `C:\path\to\a file.ext`
-pub fn decode_uri(uri: &str) -> Option<String> {
-    let path = match uri.split_at(8) {
-        ("file:///", path) => path,
-        _ => return None,
+///   - On Windows: `file://host/share/file.ext` -> `\\host\share\file.ext`
+///
+/// On UNIX the returned [`PathBuf`] keeps the raw decoded bytes, so non-UTF-8
+/// paths that nvim and the filesystem accept are preserved.
+pub fn decode_uri(uri: &str) -> Option<PathBuf> {
+    // Split off the `file:` scheme.
+    let rest = uri.strip_prefix("file:")?;
+
+    // Parse an optional authority: `//authority/path`.
+    let (authority, path) = if let Some(after_slashes) = rest.strip_prefix("//") {
+        match after_slashes.find('/') {
+            Some(idx) => (&after_slashes[..idx], &after_slashes[idx..]),
+            // `file://host` with no path component.
+            None => (after_slashes, ""),
+        }
+    } else {
+        // `file:/path` — authority-less form.
+        ("", rest)
     };
-    let path = percent_decode(path.as_bytes()).decode_utf8().ok()?;
-    if cfg!(target_os = "windows") {
+
+    #[cfg(windows)]
+    {
+        // Percent-decode the path only; the authority is kept verbatim.
+        let path = percent_decode(path.as_bytes()).decode_utf8().ok()?;
+        let local = authority.is_empty() || authority.eq_ignore_ascii_case("localhost");
+
         lazy_static! {
             static ref SLASH: Regex = Regex::new(r"/").unwrap();
         }
-        Some(String::from(SLASH.replace_all(&*path, r"\")))
+        let s = if local {
+            // Strip the leading slash from a `/C:/...` drive path.
+            let path = if starts_with_drive_letter(&path) {
+                &path[1..]
+            } else {
+                &path[..]
+            };
+            String::from(SLASH.replace_all(path, r"\"))
+        } else {
+            // A non-empty authority denotes a UNC share: `\\host\share\...`.
+            format!(r"\\{}{}", authority, SLASH.replace_all(&path, r"\"))
+        };
+        Some(PathBuf::from(s))
+    }
+    #[cfg(not(windows))]
+    {
+        // On UNIX a remote authority has no meaning; keep the local path, and
+        // keep the raw bytes so non-UTF-8 filenames survive.
+        let _ = authority;
+        let bytes = percent_decode(path.as_bytes()).collect::<Vec<u8>>();
+        Some(PathBuf::from(OsString::from_vec(bytes)))
+    }
+}
+
+/// Backwards-compatible shim returning a lossy `String`, for callers that have
+/// not yet moved to the [`PathBuf`]-based [`decode_uri`].
+pub fn decode_uri_str(uri: &str) -> Option<String> {
+    decode_uri(uri).map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Bytes that must be percent-encoded in a `file://` URI path: everything
+/// except the unreserved set (`A-Za-z0-9-._~`) and the `/` path separator.
+const PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Encode a filesystem path as a `file` URI, the inverse of [`decode_uri`].
+///
+///   - On UNIX: `/path/to/a file.ext` -> `file:///path/to/a%20file.ext`
+///   - On Windows: `C:\path\to\file.ext` -> `file:///C%3A/path/to/file.ext`
+///   - On Windows: `\\server\share\file.ext` -> `file://server/share/file.ext`
+pub fn encode_file_uri(path: &str) -> String {
+    if cfg!(target_os = "windows") {
+        lazy_static! {
+            static ref BACKSLASH: Regex = Regex::new(r"\\").unwrap();
+        }
+        // Rewrite separators before encoding so only path bytes get escaped.
+        let slashed = BACKSLASH.replace_all(path, "/");
+        let (authority, path): (&str, Cow<str>) = if let Some(unc) = slashed.strip_prefix("//") {
+            // UNC `\\server\share\...` -> authority `server`, path `/share/...`.
+            match unc.find('/') {
+                Some(idx) => (&unc[..idx], Cow::from(&unc[idx..])),
+                None => (unc, Cow::from("")),
+            }
+        } else {
+            // Drive path `C:/...` -> authority-less `/C:/...`.
+            ("", Cow::from(format!("/{}", slashed)))
+        };
+        let encoded = utf8_percent_encode(&path, PATH_ENCODE_SET).to_string();
+        format!("file://{}{}", authority, encoded)
     } else {
-        Some("/".to_owned() + &path)
+        let encoded = utf8_percent_encode(path, PATH_ENCODE_SET).to_string();
+        format!("file://{}", encoded)
     }
 }
 
@@ -95,36 +239,167 @@ mod tests {
         assert_eq!("a", res[0]);
         assert_eq!("b,c", res[1]);
     }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_decode_uri() {
+        assert_eq!(
+            decode_uri("file:///path/to/a%20file.ext").unwrap(),
+            PathBuf::from("/path/to/a file.ext")
+        );
+        // Authority forms that resolve to the local machine.
+        assert_eq!(
+            decode_uri("file://localhost/etc/hosts").unwrap(),
+            PathBuf::from("/etc/hosts")
+        );
+        assert_eq!(
+            decode_uri("file:/etc/hosts").unwrap(),
+            PathBuf::from("/etc/hosts")
+        );
+        // A non-`file` scheme is rejected.
+        assert_eq!(decode_uri("http://example.com/"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escape_path() {
+        assert_eq!(
+            escape_path(Path::new("/a b/c*d")),
+            OsString::from(r"/a\ b/c\*d")
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_encode_file_uri() {
+        assert_eq!(
+            encode_file_uri("/path/to/a file.ext"),
+            "file:///path/to/a%20file.ext"
+        );
+        // Unreserved characters are left untouched.
+        assert_eq!(encode_file_uri("/a-b_c.d~e"), "file:///a-b_c.d~e");
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_file_uri_round_trip() {
+        for path in &["/path/to/a file.ext", "/tmp/100% done/öäü.txt"] {
+            assert_eq!(decode_uri(&encode_file_uri(path)).unwrap(), PathBuf::from(*path));
+        }
+    }
+
+    #[test]
+    fn test_try_log_message_paths() {
+        // Exercise every arm so the message-formatting path actually compiles.
+        fn bare(r: Result<u8, ()>) -> u8 {
+            crate::try_log!(r, 0)
+        }
+        fn fmt_only(r: Result<u8, ()>) -> u8 {
+            crate::try_log!(r, 0, ::log::Level::Error, "operation failed")
+        }
+        fn fmt_args(r: Result<u8, ()>) -> u8 {
+            crate::try_log!(r, 0, ::log::Level::Warn, "operation failed: {}", 42)
+        }
+
+        assert_eq!(bare(Ok(7)), 7);
+        assert_eq!(bare(Err(())), 0);
+        assert_eq!(fmt_only(Err(())), 0);
+        assert_eq!(fmt_args(Err(())), 0);
+    }
 }
 
 
+/// Report a user-facing error.
+///
+/// The failure is always logged via `error!`. When a GTK main loop is running
+/// it is additionally surfaced through a modal [`gtk::MessageDialog`] so the
+/// user sees an actionable message instead of the application vanishing; in
+/// headless or test contexts the dialog is skipped and only the log remains.
+///
+/// This backs [`try_dialog!`]. Neither `try_dialog!` nor this function is
+/// called from elsewhere in the crate, so no existing operation reports its
+/// failures through a dialog.
+pub fn report_error(context: &str, err: &impl Debug) {
+    error!("{}: {:?}", context, err);
+
+    if gtk::main_level() > 0 {
+        use gtk::prelude::*;
+
+        let dialog = gtk::MessageDialog::new(
+            None::<&gtk::Window>,
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Error,
+            gtk::ButtonsType::Close,
+            context,
+        );
+        dialog.set_secondary_text(Some(&format!("{:?}", err)));
+        dialog.run();
+        dialog.close();
+    }
+}
+
+#[macro_export]
+/// Try to unwrap a `Result<T, E>`. If there is a value `T`, yield it,
+/// otherwise report the error to the user via [`report_error`] and exit the
+/// program. Mirrors [`try_e!`] but shows a dialog when a main loop is running.
+macro_rules! try_dialog {
+    ($expr:expr, $context:expr) => {
+        match $expr {
+            ::std::result::Result::Ok(val) => val,
+            ::std::result::Result::Err(err) => {
+                $crate::misc::report_error($context, &err);
+                ::std::process::exit(1);
+            }
+        }
+    };
+}
+
+#[macro_export]
+/// Try to unwrap a `Result<T, E>`. If there is a value `T`, yield it,
+/// otherwise log the error and return from the function with the given value.
+///
+/// The message is logged at [`log::Level::Warn`] unless an explicit level is
+/// given. Any trailing tokens are forwarded verbatim to [`log::log!`], so the
+/// full `format!`-style message path — including `format!` arguments — is
+/// exercised rather than double-wrapped:
+///
+/// ```ignore
+/// try_log!(parse(s), ());                                   // bare
+/// try_log!(parse(s), (), ::log::Level::Error, "bad input"); // format-only
+/// try_log!(parse(s), (), ::log::Level::Warn, "bad {}", s);  // format + args
+/// ```
+macro_rules! try_log {
+    ($expr:expr, $ret:expr) => {
+        match $expr {
+            ::std::result::Result::Ok(val) => val,
+            ::std::result::Result::Err(err) => {
+                ::log::warn!("{:?}", err);
+                return $ret;
+            }
+        }
+    };
+    ($expr:expr, $ret:expr, $level:expr, $($arg:tt)*) => {
+        match $expr {
+            ::std::result::Result::Ok(val) => val,
+            ::std::result::Result::Err(err) => {
+                ::log::log!($level, "Original error: {:?}", err);
+                ::log::log!($level, $($arg)*);
+                return $ret;
+            }
+        }
+    };
+}
+
 #[macro_export]
 /// Try to unwrap a `Result<T, E>`. If there is a value `T`, yield it,
 /// otherwise print a warning and return from the function with the given value.
 macro_rules! try_wr {
-    ($expr:expr, $ret:expr) => (match $expr {
-        ::std::result::Result::Ok(val) => val,
-        ::std::result::Result::Err(err) => {
-            warn!("{:?}", err);
-            return $ret;
-        },
-    });
-    ($expr:expr, $ret:expr, $fmt:expr) => (match $expr {
-        ::std::result::Result::Ok(val) => val,
-        ::std::result::Result::Err(err) => {
-            warn!("Original error: {:?}", err);
-            warn!($fmt);
-            return $ret;
-        },
-    });
-    ($expr:expr, $ret:expr, $fmt:expr, $($arg:tt)+) => (match $expr {
-        ::std::result::Result::Ok(val) => val,
-        ::std::result::Result::Err(err) => {
-            warn!("Original error: {:?}", err);
-            warn!(format!($fmt, $(arg)+));
-            return $ret;
-        },
-    })
+    ($expr:expr, $ret:expr) => {
+        try_log!($expr, $ret)
+    };
+    ($expr:expr, $ret:expr, $($arg:tt)*) => {
+        try_log!($expr, $ret, ::log::Level::Warn, $($arg)*)
+    };
 }
 
 #[macro_export]
@@ -134,12 +409,9 @@ macro_rules! try_w {
     ($expr:expr) => {
         try_wr!($expr, ())
     };
-    ($expr:expr, $fmt:expr, $($arg:tt)+) => {
-        try_wr!($expr, (), $fmt, $(arg)+)
+    ($expr:expr, $($arg:tt)*) => {
+        try_wr!($expr, (), $($arg)*)
     };
-    ($expr:expr, $fmt:expr) => {
-        try_wr!($expr, (), $fmt)
-    }
 }
 
 #[macro_export]
@@ -166,19 +438,11 @@ macro_rules! try_e {
             ::std::process::exit(1);
         },
     });
-    ($expr:expr, $fmt:expr) => (match $expr {
-        ::std::result::Result::Ok(val) => val,
-        ::std::result::Result::Err(err) => {
-            error!("Original error: {:?}", err);
-            error!($fmt);
-            std::process::exit(1);
-        },
-    });
-    ($expr:expr, $fmt:expr, $($arg:tt)+) => (match $expr {
+    ($expr:expr, $($arg:tt)*) => (match $expr {
         ::std::result::Result::Ok(val) => val,
         ::std::result::Result::Err(err) => {
             error!("Original error: {:?}", err);
-            error!(format!($fmt, $(arg)+));
+            error!($($arg)*);
             std::process::exit(1);
         },
     })